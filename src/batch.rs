@@ -0,0 +1,147 @@
+use futures::prelude::*;
+use pin_project::pin_project;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use std::time::Duration;
+use tokio::time::{sleep, Sleep};
+
+use super::Block;
+
+/// Batches a stream of [`Block`]s into `Vec<Block>`s, flushing when the batch
+/// reaches `max_size` or a per-batch timeout elapses.
+///
+/// Built after tokio-stream's chunks-with-timeout adapter: the timer is armed
+/// when the first block of a batch arrives and reset on every flush. The final
+/// partial batch is emitted before the stream ends, and a decode error is
+/// propagated immediately.
+///
+/// The `deadline: Option<Sleep>` field makes `ChunksTimeout<S>` `!Unpin` for
+/// any `S`, since `Sleep` itself is never `Unpin`. Callers driving this with
+/// `StreamExt::next` (which requires `Self: Unpin`) need to pin it first,
+/// e.g. with `tokio::pin!` or `Box::pin`.
+#[pin_project]
+pub struct ChunksTimeout<S> {
+    #[pin]
+    stream: S,
+    #[pin]
+    deadline: Option<Sleep>,
+    duration: Duration,
+    items: Vec<Block>,
+    max_size: usize,
+    done: bool,
+}
+
+impl<S> ChunksTimeout<S>
+where
+    S: Stream<Item = super::Result<Block>>,
+{
+    pub fn new(stream: S, max_size: usize, duration: Duration) -> Self {
+        ChunksTimeout {
+            stream,
+            deadline: None,
+            duration,
+            items: Vec::with_capacity(max_size),
+            max_size,
+            done: false,
+        }
+    }
+}
+
+impl<S> Stream for ChunksTimeout<S>
+where
+    S: Stream<Item = super::Result<Block>>,
+{
+    type Item = super::Result<Vec<Block>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.project();
+
+        if *this.done {
+            return Poll::Ready(None);
+        }
+
+        loop {
+            match this.stream.as_mut().poll_next(cx) {
+                Poll::Ready(Some(Ok(block))) => {
+                    if this.items.is_empty() {
+                        // Arm the flush timer for the first block of the batch.
+                        this.deadline
+                            .set(Some(sleep(*this.duration)));
+                    }
+                    this.items.push(block);
+
+                    if this.items.len() >= *this.max_size {
+                        this.deadline.set(None);
+                        let batch =
+                            mem::replace(this.items, Vec::with_capacity(*this.max_size));
+                        return Poll::Ready(Some(Ok(batch)));
+                    }
+                }
+                Poll::Ready(Some(Err(e))) => {
+                    // Propagate decode errors immediately and stop.
+                    *this.done = true;
+                    return Poll::Ready(Some(Err(e)));
+                }
+                Poll::Ready(None) => {
+                    *this.done = true;
+                    this.deadline.set(None);
+                    return if this.items.is_empty() {
+                        Poll::Ready(None)
+                    } else {
+                        Poll::Ready(Some(Ok(mem::take(this.items))))
+                    };
+                }
+                Poll::Pending => break,
+            }
+        }
+
+        // No more blocks ready right now; flush if the timer has fired.
+        if !this.items.is_empty() {
+            if let Some(deadline) = this.deadline.as_mut().as_pin_mut() {
+                ready!(deadline.poll(cx));
+                this.deadline.set(None);
+                let batch = mem::replace(this.items, Vec::with_capacity(*this.max_size));
+                return Poll::Ready(Some(Ok(batch)));
+            }
+        }
+
+        Poll::Pending
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::get_dummy_block_pair;
+
+    #[tokio::test]
+    async fn test_flushes_on_size() {
+        let (block, _) = get_dummy_block_pair();
+        let stream = futures::stream::iter(vec![Ok(block.clone()), Ok(block.clone()), Ok(block)]);
+
+        let mut batched = Box::pin(ChunksTimeout::new(stream, 2, Duration::from_secs(60)));
+
+        let first = batched.next().await.unwrap().unwrap();
+        assert_eq!(2, first.len());
+
+        let second = batched.next().await.unwrap().unwrap();
+        assert_eq!(1, second.len());
+
+        assert!(batched.next().await.is_none());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_flushes_on_timeout() {
+        let (block, _) = get_dummy_block_pair();
+        // Stay pending after the one item so end-of-stream can't flush the
+        // batch first; only the armed timer should produce it.
+        let stream = futures::stream::iter(vec![Ok(block)]).chain(futures::stream::pending());
+
+        let mut batched = Box::pin(ChunksTimeout::new(stream, 8, Duration::from_millis(50)));
+
+        let batch = batched.next().await.unwrap().unwrap();
+        assert_eq!(1, batch.len());
+    }
+}
@@ -1,11 +1,18 @@
 use bytes::{buf::Buf, Bytes};
+use sha2::{Digest, Sha256};
 use std::{fmt, io::Cursor};
 
 use super::error::Error;
 
-const PARENT_HASH_LEN: usize = 32;
-const BLOCK_ID_LEN: usize = 8;
-const PAYLOAD_SIZE_LEN: usize = 4;
+pub(crate) const VERSION: u8 = 0x1;
+pub(crate) const PARENT_HASH_LEN: usize = 32;
+pub(crate) const BLOCK_ID_LEN: usize = 8;
+pub(crate) const PAYLOAD_SIZE_LEN: usize = 4;
+
+/// Message type carried in the high nibble of the version byte. `Block` is
+/// the only type `BlockCodec`/`BlockStream` understand; the rest live in
+/// [`Message`](crate::Message).
+pub(crate) const TYPE_BLOCK: u8 = 0x0;
 
 pub type ParentHash = [u8; PARENT_HASH_LEN];
 
@@ -24,7 +31,7 @@ pub type ParentHash = [u8; PARENT_HASH_LEN];
 /// * Following 4 bytes indicates the lenght of the remaining data (payload).
 /// * Next 32 bytes are for parent hash and other 8 bytes are for block number.
 /// * Everything remaining are the contents of the block.
-#[derive(Clone)]
+#[derive(Debug, Clone)]
 pub struct Block {
     /// Block number, monotonically increasing as the chain grows.
     pub block_number: u64,
@@ -44,49 +51,103 @@ impl Block {
     }
 
     pub fn check(src: &mut Cursor<&[u8]>) -> Result<(), Error> {
-        match get_u8(src)? {
-            0x1 => {
-                let len = get_payload_len(src)?;
-
-                // check if we have enough data for parsing.
-                if src.get_ref().len() < len as usize {
-                    return Err(Error::incomplete_error());
-                }
+        // Low nibble is the protocol version, high nibble is the message type
+        // shared with Message. BlockStream/BlockCodec only understand Block
+        // frames, so anything else is a hard error, not incomplete data.
+        let header = get_u8(src)?;
+        let version = header & 0x0F;
+        if version != VERSION {
+            return Err(Error::unsupported_version_error(version));
+        }
+        let message_type = header >> 4;
+        if message_type != TYPE_BLOCK {
+            return Err(Error::unknown_message_type_error(message_type));
+        }
 
-                Ok(())
-            }
+        let len = get_payload_len(src)?;
 
-            _ => Err(Error::incomplete_error()),
+        // check if we have enough data for parsing.
+        if src.remaining() < len as usize {
+            return Err(Error::incomplete_error());
         }
+
+        Ok(())
     }
 
     pub fn parse(src: &mut Cursor<&[u8]>) -> Result<Block, Error> {
-        match get_u8(src)? {
-            // Version one, represented as 0b0000_0001 byte.
-            // First most significant bits are reserved and last four bits are for the wire protocol
-            // version.
-            0x1 => {
-                let len = get_payload_len(src)?;
-                let len = len as usize;
+        let header = get_u8(src)?;
+        let version = header & 0x0F;
+        if version != VERSION {
+            return Err(Error::unsupported_version_error(version));
+        }
+        let message_type = header >> 4;
+        if message_type != TYPE_BLOCK {
+            return Err(Error::unknown_message_type_error(message_type));
+        }
 
-                let parent_hash = get_parent_hash(src)?;
-                let count = get_block_id(src)?;
+        let len = get_payload_len(src)? as usize;
+        Block::parse_body(src, len)
+    }
 
-                if src.remaining() < len {
-                    return Err(Error::incomplete_error());
-                }
+    /// Parses a block body (parent hash, block number, content) from `src`
+    /// assuming the version byte and `len` prefix have already been consumed.
+    /// `len` is the full payload length, including the fixed header fields.
+    pub(crate) fn parse_body(src: &mut Cursor<&[u8]>, len: usize) -> Result<Block, Error> {
+        let min = PARENT_HASH_LEN + BLOCK_ID_LEN;
+        if len < min {
+            return Err(Error::frame_too_small_error(len, min));
+        }
 
-                // As cursor moves we need to account for already read values.
-                let len = len - PARENT_HASH_LEN - BLOCK_ID_LEN;
-                let payload = Bytes::copy_from_slice(&src.chunk()[..len]);
+        let parent_hash = get_parent_hash(src)?;
+        let count = get_block_id(src)?;
 
-                skip(src, len)?;
+        // `len` covers parent_hash and block_id too, already consumed above,
+        // so what's left to read is just the content.
+        let content_len = len - min;
+        if src.remaining() < content_len {
+            return Err(Error::incomplete_error());
+        }
 
-                Ok(Block::new(parent_hash, count, &payload[..]))
-            }
+        let payload = Bytes::copy_from_slice(&src.chunk()[..content_len]);
+        skip(src, content_len)?;
 
-            _ => Err(Error::incomplete_error()),
-        }
+        Ok(Block::new(parent_hash, count, &payload[..]))
+    }
+
+    /// Computes the block's identity hash using the default [`Sha256Hasher`].
+    ///
+    /// Hashing the header fields plus content gives a stable identifier, so the
+    /// `parent_hash` a peer claims is not used as the block's identity; this
+    /// is what chain verification and
+    /// [`find_common_ancestor`](crate::find_common_ancestor) compare against.
+    pub fn hash(&self) -> ParentHash {
+        Sha256Hasher.hash(self)
+    }
+}
+
+/// Digest used to derive a block's identity and to link it to its successor.
+///
+/// Implement this to swap SHA-256 for another digest when verifying a chain.
+pub trait BlockHasher {
+    /// Hashes `block` into a 32-byte [`ParentHash`].
+    fn hash(&self, block: &Block) -> ParentHash;
+}
+
+/// Default [`BlockHasher`]: SHA-256 over the parent hash, block number and
+/// content.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Sha256Hasher;
+
+impl BlockHasher for Sha256Hasher {
+    fn hash(&self, block: &Block) -> ParentHash {
+        let mut hasher = Sha256::new();
+        hasher.update(block.parent_hash);
+        hasher.update(block.block_number.to_be_bytes());
+        hasher.update(&block.content);
+
+        let mut out = [0u8; PARENT_HASH_LEN];
+        out.copy_from_slice(&hasher.finalize());
+        out
     }
 }
 
@@ -96,10 +157,10 @@ impl fmt::Display for Block {
     }
 }
 
-fn get_block_id(src: &mut Cursor<&[u8]>) -> Result<u64, Error> {
+pub(crate) fn get_block_id(src: &mut Cursor<&[u8]>) -> Result<u64, Error> {
     let start = src.position() as usize;
     let end = start + BLOCK_ID_LEN - 1;
-    if src.get_ref().len() > BLOCK_ID_LEN - 1 {
+    if src.get_ref().len() > end {
         // move the cursor after the hash data.
         src.set_position((end + 1) as u64);
 
@@ -112,7 +173,7 @@ fn get_block_id(src: &mut Cursor<&[u8]>) -> Result<u64, Error> {
     Err(Error::incomplete_error())
 }
 
-fn get_u8(src: &mut Cursor<&[u8]>) -> Result<u8, Error> {
+pub(crate) fn get_u8(src: &mut Cursor<&[u8]>) -> Result<u8, Error> {
     if !src.has_remaining() {
         return Err(Error::incomplete_error());
     }
@@ -120,10 +181,10 @@ fn get_u8(src: &mut Cursor<&[u8]>) -> Result<u8, Error> {
     Ok(src.get_u8())
 }
 
-fn get_parent_hash(src: &mut Cursor<&[u8]>) -> Result<ParentHash, Error> {
+pub(crate) fn get_parent_hash(src: &mut Cursor<&[u8]>) -> Result<ParentHash, Error> {
     let start = src.position() as usize;
     let end = start + PARENT_HASH_LEN - 1;
-    if src.get_ref().len() > PARENT_HASH_LEN - 1 {
+    if src.get_ref().len() > end {
         // move the cursor after the hash data.
         src.set_position((end + 1) as u64);
 
@@ -136,7 +197,21 @@ fn get_parent_hash(src: &mut Cursor<&[u8]>) -> Result<ParentHash, Error> {
     Err(Error::incomplete_error())
 }
 
-fn get_payload_len(src: &mut Cursor<&[u8]>) -> Result<u32, Error> {
+/// Reads just the version byte and 4-byte length prefix, returning the
+/// declared payload length without advancing past it. Used to enforce a
+/// `max_frame_length` before buffering the advertised payload. Returns `None`
+/// when the header itself is not yet fully buffered.
+pub(crate) fn peek_payload_len(src: &[u8]) -> Option<usize> {
+    if src.len() < 1 + PAYLOAD_SIZE_LEN {
+        return None;
+    }
+
+    let mut buf = [0u8; PAYLOAD_SIZE_LEN];
+    buf.copy_from_slice(&src[1..1 + PAYLOAD_SIZE_LEN]);
+    Some(u32::from_be_bytes(buf) as usize)
+}
+
+pub(crate) fn get_payload_len(src: &mut Cursor<&[u8]>) -> Result<u32, Error> {
     // check if we have 4 bytes available for readying.
     let start = src.position() as usize;
     let end = start + PAYLOAD_SIZE_LEN - 1;
@@ -153,7 +228,7 @@ fn get_payload_len(src: &mut Cursor<&[u8]>) -> Result<u32, Error> {
     Err(Error::incomplete_error())
 }
 
-fn skip(src: &mut Cursor<&[u8]>, n: usize) -> Result<(), Error> {
+pub(crate) fn skip(src: &mut Cursor<&[u8]>, n: usize) -> Result<(), Error> {
     if src.remaining() < n {
         return Err(Error::incomplete_error());
     }
@@ -164,6 +239,7 @@ fn skip(src: &mut Cursor<&[u8]>, n: usize) -> Result<(), Error> {
 
 #[cfg(test)]
 mod tests {
+    use crate::error::ErrorKind;
     use crate::utils::get_dummy_block_pair;
 
     use super::*;
@@ -190,6 +266,56 @@ mod tests {
         assert_eq!(expected.content, block.content);
     }
 
+    #[test]
+    fn test_check_rejects_non_block_message_type() {
+        // High nibble 0x1 is Message::GetBlocks; BlockStream/BlockCodec only
+        // understand Block frames and must reject the rest.
+        let (_, reader) = get_dummy_block_pair();
+        let mut buf = BytesMut::from(&reader[..]);
+        buf[0] |= 0x10;
+
+        let mut src = Cursor::new(&buf[..]);
+        assert!(matches!(
+            Block::check(&mut src).unwrap_err().kind(),
+            ErrorKind::UnknownMessageType { message_type: 1 }
+        ));
+    }
+
+    #[test]
+    fn test_parse_rejects_undersized_payload() {
+        // A declared length shorter than parent_hash + block_id must not
+        // underflow the `len - PARENT_HASH_LEN - BLOCK_ID_LEN` subtraction.
+        let mut buf = BytesMut::new();
+        buf.put_u8(VERSION);
+        buf.put_u32(4);
+        buf.put(&[0u8; 4][..]);
+
+        let mut src = Cursor::new(&buf[..]);
+        assert!(matches!(
+            Block::parse(&mut src).unwrap_err().kind(),
+            ErrorKind::FrameTooSmall { len: 4, min } if *min == PARENT_HASH_LEN + BLOCK_ID_LEN
+        ));
+    }
+
+    #[test]
+    fn test_parse_reports_incomplete_instead_of_panicking_on_short_buffer() {
+        // A plausible mid-stream TCP chunk: the header declares a full
+        // parent_hash + block_id payload, but only the parent_hash has
+        // arrived so far. get_block_id's bounds check must be relative to
+        // the buffer, not an absolute threshold, or this panics on a slice
+        // out of range instead of reporting Incomplete.
+        let mut buf = BytesMut::new();
+        buf.put_u8(VERSION);
+        buf.put_u32((PARENT_HASH_LEN + BLOCK_ID_LEN) as u32);
+        buf.put(&[0u8; PARENT_HASH_LEN][..]);
+
+        let mut src = Cursor::new(&buf[..]);
+        assert!(matches!(
+            Block::parse(&mut src).unwrap_err().kind(),
+            ErrorKind::Incomplete
+        ));
+    }
+
     #[test]
     fn test_get_payload_len() {
         let cases = [
@@ -3,6 +3,19 @@ use std::fmt::{self, Display, Formatter};
 #[derive(Debug, PartialEq)]
 pub enum ErrorKind {
     Incomplete,
+    FrameTooLarge { len: usize, max: usize },
+    FrameTooSmall { len: usize, min: usize },
+    BrokenChain {
+        expected: [u8; 32],
+        found: [u8; 32],
+        block_number: u64,
+    },
+    UnsupportedVersion {
+        version: u8,
+    },
+    UnknownMessageType {
+        message_type: u8,
+    },
     Other(String),
 }
 
@@ -10,6 +23,31 @@ impl Display for ErrorKind {
     fn fmt(&self, fmt: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
             ErrorKind::Incomplete => write!(fmt, "Incomplete data for the block"),
+            ErrorKind::FrameTooLarge { len, max } => write!(
+                fmt,
+                "Declared frame length {} exceeds maximum {}",
+                len, max
+            ),
+            ErrorKind::FrameTooSmall { len, min } => write!(
+                fmt,
+                "Declared frame length {} is smaller than the minimum {}",
+                len, min
+            ),
+            ErrorKind::BrokenChain {
+                expected,
+                found,
+                block_number,
+            } => write!(
+                fmt,
+                "Broken chain at block {}: expected parent {:?}, found {:?}",
+                block_number, expected, found
+            ),
+            ErrorKind::UnsupportedVersion { version } => {
+                write!(fmt, "Unsupported wire protocol version: {}", version)
+            }
+            ErrorKind::UnknownMessageType { message_type } => {
+                write!(fmt, "Unknown message type: {}", message_type)
+            }
             ErrorKind::Other(msg) => write!(fmt, "Service error: {}", msg),
         }
     }
@@ -30,6 +68,26 @@ impl Error {
     pub fn incomplete_error() -> Self {
         ErrorKind::Incomplete.into_err()
     }
+    pub fn frame_too_large_error(len: usize, max: usize) -> Self {
+        ErrorKind::FrameTooLarge { len, max }.into_err()
+    }
+    pub fn frame_too_small_error(len: usize, min: usize) -> Self {
+        ErrorKind::FrameTooSmall { len, min }.into_err()
+    }
+    pub fn broken_chain_error(expected: [u8; 32], found: [u8; 32], block_number: u64) -> Self {
+        ErrorKind::BrokenChain {
+            expected,
+            found,
+            block_number,
+        }
+        .into_err()
+    }
+    pub fn unsupported_version_error(version: u8) -> Self {
+        ErrorKind::UnsupportedVersion { version }.into_err()
+    }
+    pub fn unknown_message_type_error(message_type: u8) -> Self {
+        ErrorKind::UnknownMessageType { message_type }.into_err()
+    }
     pub fn other_error<M: Into<String>>(msg: M) -> Self {
         ErrorKind::Other(msg.into()).into_err()
     }
@@ -46,4 +104,12 @@ impl fmt::Display for Error {
 
 impl std::error::Error for Error {}
 
+// Required by tokio_util::codec::{Decoder, Encoder}'s `Error: From<io::Error>`
+// bound, so BlockCodec can surface I/O failures through the same error type.
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Error::other_error(err.to_string())
+    }
+}
+
 pub type Result<T> = core::result::Result<T, Error>;
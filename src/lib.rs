@@ -0,0 +1,19 @@
+mod batch;
+mod block;
+mod codec;
+mod error;
+mod message;
+mod reader;
+mod stream;
+#[cfg(test)]
+mod utils;
+mod verify;
+
+pub use batch::ChunksTimeout;
+pub use block::{Block, BlockHasher, ParentHash, Sha256Hasher};
+pub use codec::BlockCodec;
+pub use error::{Error, ErrorKind, Result};
+pub use message::Message;
+pub use reader::BlockReader;
+pub use stream::{find_common_ancestor, read_blocks, read_messages, BlockStream, MessageStream};
+pub use verify::VerifiedBlockStream;
@@ -0,0 +1,219 @@
+use bytes::Buf;
+use std::io::Cursor;
+
+use super::block::{
+    get_block_id, get_parent_hash, get_payload_len, get_u8, Block, ParentHash, PARENT_HASH_LEN,
+    TYPE_BLOCK, VERSION,
+};
+use super::error::Error;
+
+// Remaining message types carried in the high nibble of the version byte;
+// TYPE_BLOCK lives in block.rs since Block::check/parse validate it too.
+const TYPE_GET_BLOCKS: u8 = 0x1;
+const TYPE_INVENTORY: u8 = 0x2;
+const TYPE_PONG: u8 = 0x3;
+
+/// A framed control or data message multiplexed over a single connection.
+///
+/// The wire framing is shared with [`Block`]: a version byte whose high nibble
+/// selects the variant, a 4-byte big-endian payload length and the payload
+/// itself. This turns the one-way block firehose into a request/response
+/// protocol.
+#[derive(Clone)]
+pub enum Message {
+    /// A full block, the payload used by [`BlockStream`](crate::BlockStream).
+    Block(Block),
+    /// Request the chain starting from the given block number.
+    GetBlocks { from: u64 },
+    /// Advertise the parent hashes a peer holds.
+    Inventory(Vec<ParentHash>),
+    /// Keep-alive / liveness response.
+    Pong,
+}
+
+impl Message {
+    /// Cheaply validates that a complete, well-formed frame is buffered without
+    /// allocating a [`Message`]. An unknown version or type is a hard error.
+    pub fn check(src: &mut Cursor<&[u8]>) -> Result<(), Error> {
+        let header = get_u8(src)?;
+        let version = header & 0x0F;
+        if version != VERSION {
+            return Err(Error::unsupported_version_error(version));
+        }
+
+        match header >> 4 {
+            TYPE_BLOCK | TYPE_GET_BLOCKS | TYPE_INVENTORY | TYPE_PONG => {}
+            other => return Err(Error::unknown_message_type_error(other)),
+        }
+
+        let len = get_payload_len(src)?;
+        if src.remaining() < len as usize {
+            return Err(Error::incomplete_error());
+        }
+
+        Ok(())
+    }
+
+    pub fn parse(src: &mut Cursor<&[u8]>) -> Result<Message, Error> {
+        let header = get_u8(src)?;
+        let version = header & 0x0F;
+        if version != VERSION {
+            return Err(Error::unsupported_version_error(version));
+        }
+        let message_type = header >> 4;
+
+        let len = get_payload_len(src)? as usize;
+
+        match message_type {
+            TYPE_BLOCK => Ok(Message::Block(Block::parse_body(src, len)?)),
+            TYPE_GET_BLOCKS => {
+                if src.remaining() < len {
+                    return Err(Error::incomplete_error());
+                }
+                Ok(Message::GetBlocks {
+                    from: get_block_id(src)?,
+                })
+            }
+            TYPE_INVENTORY => {
+                if src.remaining() < len {
+                    return Err(Error::incomplete_error());
+                }
+                if len % PARENT_HASH_LEN != 0 {
+                    return Err(Error::other_error(format!(
+                        "inventory frame length {} is not a multiple of parent hash length {}",
+                        len, PARENT_HASH_LEN
+                    )));
+                }
+                let mut hashes = Vec::with_capacity(len / PARENT_HASH_LEN);
+                for _ in 0..len / PARENT_HASH_LEN {
+                    hashes.push(get_parent_hash(src)?);
+                }
+                Ok(Message::Inventory(hashes))
+            }
+            TYPE_PONG => Ok(Message::Pong),
+            other => Err(Error::unknown_message_type_error(other)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BLOCK_ID_LEN;
+    use crate::error::ErrorKind;
+    use bytes::{BufMut, BytesMut};
+
+    fn frame(message_type: u8, payload: &[u8]) -> BytesMut {
+        let mut buf = BytesMut::new();
+        buf.put_u8((message_type << 4) | VERSION);
+        buf.put_u32(payload.len() as u32);
+        buf.put(payload);
+        buf
+    }
+
+    #[test]
+    fn test_check_and_parse_get_blocks() {
+        let buf = frame(TYPE_GET_BLOCKS, &42u64.to_be_bytes());
+
+        let mut src = Cursor::new(&buf[..]);
+        assert_eq!(Ok(()), Message::check(&mut src));
+
+        let mut src = Cursor::new(&buf[..]);
+        match Message::parse(&mut src).unwrap() {
+            Message::GetBlocks { from } => assert_eq!(42, from),
+            _ => panic!("expected GetBlocks, got a different variant"),
+        }
+    }
+
+    #[test]
+    fn test_check_and_parse_inventory() {
+        let hashes = [[1u8; PARENT_HASH_LEN], [2u8; PARENT_HASH_LEN]];
+        let payload: Vec<u8> = hashes.concat();
+        let buf = frame(TYPE_INVENTORY, &payload);
+
+        let mut src = Cursor::new(&buf[..]);
+        assert_eq!(Ok(()), Message::check(&mut src));
+
+        let mut src = Cursor::new(&buf[..]);
+        match Message::parse(&mut src).unwrap() {
+            Message::Inventory(got) => assert_eq!(hashes.to_vec(), got),
+            _ => panic!("expected Inventory, got a different variant"),
+        }
+    }
+
+    #[test]
+    fn test_check_and_parse_pong() {
+        let buf = frame(TYPE_PONG, &[]);
+
+        let mut src = Cursor::new(&buf[..]);
+        assert_eq!(Ok(()), Message::check(&mut src));
+
+        let mut src = Cursor::new(&buf[..]);
+        assert!(matches!(Message::parse(&mut src).unwrap(), Message::Pong));
+    }
+
+    #[test]
+    fn test_check_and_parse_block() {
+        let (expected, reader) = crate::utils::get_dummy_block_pair();
+
+        let mut src = Cursor::new(&reader[..]);
+        assert_eq!(Ok(()), Message::check(&mut src));
+
+        let mut src = Cursor::new(&reader[..]);
+        match Message::parse(&mut src).unwrap() {
+            Message::Block(block) => {
+                assert_eq!(expected.parent_hash, block.parent_hash);
+                assert_eq!(expected.block_number, block.block_number);
+            }
+            _ => panic!("expected Block, got a different variant"),
+        }
+    }
+
+    #[test]
+    fn test_check_rejects_unknown_message_type() {
+        let buf = frame(0xF, &[]);
+
+        let mut src = Cursor::new(&buf[..]);
+        assert!(matches!(
+            Message::check(&mut src).unwrap_err().kind(),
+            ErrorKind::UnknownMessageType { message_type: 0xF }
+        ));
+    }
+
+    #[test]
+    fn test_check_rejects_unsupported_version() {
+        let mut buf = frame(TYPE_PONG, &[]);
+        buf[0] = (TYPE_PONG << 4) | 0x2;
+
+        let mut src = Cursor::new(&buf[..]);
+        assert!(matches!(
+            Message::check(&mut src).unwrap_err().kind(),
+            ErrorKind::UnsupportedVersion { version: 0x2 }
+        ));
+    }
+
+    #[test]
+    fn test_parse_rejects_inventory_length_not_multiple_of_parent_hash_len() {
+        // One full hash plus a trailing byte that doesn't make up a second one.
+        let mut payload = vec![0u8; PARENT_HASH_LEN + 1];
+        payload[0] = 1;
+        let buf = frame(TYPE_INVENTORY, &payload);
+
+        let mut src = Cursor::new(&buf[..]);
+        assert!(matches!(
+            Message::parse(&mut src).unwrap_err().kind(),
+            ErrorKind::Other(_)
+        ));
+    }
+
+    #[test]
+    fn test_parse_get_blocks_reports_incomplete_on_short_buffer() {
+        let buf = frame(TYPE_GET_BLOCKS, &42u64.to_be_bytes()[..BLOCK_ID_LEN - 1]);
+
+        let mut src = Cursor::new(&buf[..]);
+        assert!(matches!(
+            Message::parse(&mut src).unwrap_err().kind(),
+            ErrorKind::Incomplete
+        ));
+    }
+}
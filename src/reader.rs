@@ -0,0 +1,136 @@
+use bytes::{Buf, BytesMut};
+use futures::stream::Stream;
+use pin_project::pin_project;
+use std::io;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::io::{AsyncBufRead, AsyncRead, ReadBuf};
+
+use super::codec::BlockCodec;
+use super::error::Error;
+use super::Block;
+
+/// Adapter turning a `Stream` of [`Block`]s back into an [`AsyncRead`].
+///
+/// This is the inverse of [`BlockStream`](crate::BlockStream) and mirrors
+/// tokio-util's `StreamReader`: each yielded block is serialized into its wire
+/// framing and the encoded bytes are drained across successive `poll_read`
+/// calls, keeping the partially-consumed frame between polls. It lets callers
+/// splice an in-memory pipeline of blocks straight into any `AsyncWrite` sink
+/// via `tokio::io::copy`.
+#[pin_project]
+pub struct BlockReader<S> {
+    #[pin]
+    stream: S,
+    codec: BlockCodec,
+    chunk: BytesMut,
+}
+
+impl<S> BlockReader<S>
+where
+    S: Stream<Item = super::Result<Block>>,
+{
+    pub fn new(stream: S) -> Self {
+        BlockReader {
+            stream,
+            codec: BlockCodec::new(),
+            chunk: BytesMut::new(),
+        }
+    }
+}
+
+fn into_io_error(err: Error) -> io::Error {
+    io::Error::new(io::ErrorKind::Other, err)
+}
+
+impl<S> AsyncRead for BlockReader<S>
+where
+    S: Stream<Item = super::Result<Block>>,
+{
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let mut this = self.project();
+
+        loop {
+            if !this.chunk.is_empty() {
+                let n = std::cmp::min(this.chunk.len(), buf.remaining());
+                buf.put_slice(&this.chunk[..n]);
+                this.chunk.advance(n);
+                return Poll::Ready(Ok(()));
+            }
+
+            match this.stream.as_mut().poll_next(cx) {
+                Poll::Pending => return Poll::Pending,
+                // End of the pipeline surfaces as EOF.
+                Poll::Ready(None) => return Poll::Ready(Ok(())),
+                Poll::Ready(Some(Ok(block))) => {
+                    // Infallible for the current codec, but propagate anyway.
+                    use tokio_util::codec::Encoder;
+                    this.codec
+                        .encode(block, this.chunk)
+                        .map_err(into_io_error)?;
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(into_io_error(e))),
+            }
+        }
+    }
+}
+
+impl<S> AsyncBufRead for BlockReader<S>
+where
+    S: Stream<Item = super::Result<Block>>,
+{
+    fn poll_fill_buf(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<&[u8]>> {
+        let this = self.project();
+
+        if this.chunk.is_empty() {
+            match this.stream.poll_next(cx) {
+                Poll::Pending => return Poll::Pending,
+                Poll::Ready(None) => {}
+                Poll::Ready(Some(Ok(block))) => {
+                    use tokio_util::codec::Encoder;
+                    this.codec
+                        .encode(block, this.chunk)
+                        .map_err(into_io_error)?;
+                }
+                Poll::Ready(Some(Err(e))) => return Poll::Ready(Err(into_io_error(e))),
+            }
+        }
+
+        Poll::Ready(Ok(&this.chunk[..]))
+    }
+
+    fn consume(self: Pin<&mut Self>, amt: usize) {
+        let this = self.project();
+        this.chunk.advance(amt);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::get_dummy_block_pair;
+    use futures::stream;
+    use tokio::io::AsyncReadExt;
+
+    #[tokio::test]
+    async fn test_reads_encoded_frame() {
+        let (block, _) = get_dummy_block_pair();
+
+        let expected = {
+            use tokio_util::codec::Encoder;
+            let mut dst = BytesMut::new();
+            BlockCodec::new().encode(block.clone(), &mut dst).unwrap();
+            dst.to_vec()
+        };
+
+        let mut reader = BlockReader::new(stream::iter(vec![Ok(block)]));
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).await.unwrap();
+
+        assert_eq!(expected, out);
+    }
+}
@@ -1,18 +1,20 @@
-use super::block::ParentHash;
+use super::batch::ChunksTimeout;
+use super::block::{peek_payload_len, ParentHash, PAYLOAD_SIZE_LEN};
+use super::codec::DEFAULT_MAX_FRAME_LENGTH;
 use super::error::{Error, ErrorKind};
+use super::message::Message;
 use super::Block;
+use std::time::Duration;
 use bytes::{Buf, BytesMut};
-use futures::future::LocalBoxFuture;
 use futures::prelude::*;
 use pin_project::pin_project;
-use std::cell::RefCell;
 use std::collections::{HashMap, HashSet};
 use std::io::Cursor;
 use std::pin::Pin;
-use std::rc::Rc;
 use std::task::Context;
 use std::task::Poll;
 use tokio::io::AsyncRead;
+use tokio::sync::mpsc;
 use tokio_util::io::poll_read_buf;
 
 const BUFFER_CAP: usize = 4096;
@@ -22,6 +24,7 @@ pub struct BlockStream<R: AsyncRead> {
     #[pin]
     reader: Option<R>,
     buf: BytesMut,
+    max_frame_length: usize,
 }
 
 impl<R: AsyncRead> BlockStream<R> {
@@ -29,8 +32,23 @@ impl<R: AsyncRead> BlockStream<R> {
         BlockStream {
             reader: Some(reader),
             buf: BytesMut::with_capacity(BUFFER_CAP),
+            max_frame_length: DEFAULT_MAX_FRAME_LENGTH,
         }
     }
+
+    /// Sets the maximum declared payload length this stream will accept before
+    /// yielding [`ErrorKind::FrameTooLarge`](crate::ErrorKind::FrameTooLarge)
+    /// and terminating.
+    pub fn max_frame_length(mut self, max: usize) -> Self {
+        self.max_frame_length = max;
+        self
+    }
+
+    /// Batches decoded blocks into `Vec<Block>`s, flushing whenever the batch
+    /// reaches `max_size` or `duration` elapses since the first buffered block.
+    pub fn chunks_timeout(self, max_size: usize, duration: Duration) -> ChunksTimeout<Self> {
+        ChunksTimeout::new(self, max_size, duration)
+    }
 }
 
 /// Returns a stream of Blocks.
@@ -59,19 +77,37 @@ impl<R: AsyncRead> Stream for BlockStream<R> {
                 Poll::Ready(None)
             }
             Poll::Ready(Ok(_)) => {
+                // Reject an over-sized frame as soon as its length prefix is
+                // decoded, reserving the exact frame size otherwise so large
+                // legitimate blocks don't thrash the allocator.
+                if let Some(len) = peek_payload_len(&this.buf) {
+                    if len > *this.max_frame_length {
+                        let max = *this.max_frame_length;
+                        self.project().reader.set(None);
+                        return Poll::Ready(Some(Err(Error::frame_too_large_error(len, max))));
+                    }
+                    let needed = 1 + PAYLOAD_SIZE_LEN + len;
+                    if this.buf.len() < needed {
+                        this.buf.reserve(needed - this.buf.len());
+                    }
+                }
+
                 let mut buf = Cursor::new(&this.buf[..]);
 
                 // check method should be faster than parse method, so it's used here for speed and
                 // lack of Block allocations that might happen while parsing.
                 match Block::check(&mut buf) {
                     Ok(_) => {
-                        let len = buf.position() as usize;
+                        // check only reads the header, so the frame length to
+                        // advance by has to come from the declared payload
+                        // length, not from where check() leaves the cursor.
+                        let frame_len = 1 + PAYLOAD_SIZE_LEN + peek_payload_len(&this.buf).unwrap();
                         buf.set_position(0);
 
                         // If parsing fails with something other than Incomplete data error, then
                         // the stream should be terminated.
                         let maybe_block = Block::parse(&mut buf);
-                        this.buf.advance(len);
+                        this.buf.advance(frame_len);
 
                         Poll::Ready(Some(maybe_block))
                     }
@@ -87,78 +123,237 @@ pub fn read_blocks<R: AsyncRead>(io: R) -> BlockStream<R> {
     BlockStream::new(io)
 }
 
+/// Sibling of [`BlockStream`] that decodes the multiplexed control protocol,
+/// yielding [`Message`]s rather than bare blocks.
+///
+/// Unknown versions or message types terminate the stream with a distinct
+/// error instead of being silently reported as incomplete data.
+#[pin_project]
+pub struct MessageStream<R: AsyncRead> {
+    #[pin]
+    reader: Option<R>,
+    buf: BytesMut,
+    max_frame_length: usize,
+}
+
+impl<R: AsyncRead> MessageStream<R> {
+    pub fn new(reader: R) -> Self {
+        MessageStream {
+            reader: Some(reader),
+            buf: BytesMut::with_capacity(BUFFER_CAP),
+            max_frame_length: DEFAULT_MAX_FRAME_LENGTH,
+        }
+    }
+
+    /// See [`BlockStream::max_frame_length`].
+    pub fn max_frame_length(mut self, max: usize) -> Self {
+        self.max_frame_length = max;
+        self
+    }
+}
+
+impl<R: AsyncRead> Stream for MessageStream<R> {
+    type Item = super::Result<Message>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let mut this = self.as_mut().project();
+
+        let reader = match this.reader.as_pin_mut() {
+            Some(r) => r,
+            None => return Poll::Ready(None),
+        };
+
+        match poll_read_buf(reader, cx, &mut this.buf) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(Err(err)) => {
+                self.project().reader.set(None);
+                Poll::Ready(Some(Err(Error::other_error(err.to_string()))))
+            }
+            Poll::Ready(Ok(0)) => {
+                self.project().reader.set(None);
+                Poll::Ready(None)
+            }
+            Poll::Ready(Ok(_)) => {
+                if let Some(len) = peek_payload_len(&this.buf) {
+                    if len > *this.max_frame_length {
+                        let max = *this.max_frame_length;
+                        self.project().reader.set(None);
+                        return Poll::Ready(Some(Err(Error::frame_too_large_error(len, max))));
+                    }
+                    let needed = 1 + PAYLOAD_SIZE_LEN + len;
+                    if this.buf.len() < needed {
+                        this.buf.reserve(needed - this.buf.len());
+                    }
+                }
+
+                let mut buf = Cursor::new(&this.buf[..]);
+
+                match Message::check(&mut buf) {
+                    Ok(_) => {
+                        // check only reads the header, so the frame length to
+                        // advance by has to come from the declared payload
+                        // length, not from where check() leaves the cursor.
+                        let frame_len = 1 + PAYLOAD_SIZE_LEN + peek_payload_len(&this.buf).unwrap();
+                        buf.set_position(0);
+
+                        let maybe_message = Message::parse(&mut buf);
+                        this.buf.advance(frame_len);
+
+                        Poll::Ready(Some(maybe_message))
+                    }
+                    Err(e) if *e.kind() == ErrorKind::Incomplete => Poll::Pending,
+                    Err(e) => {
+                        self.project().reader.set(None);
+                        Poll::Ready(Some(Err(e)))
+                    }
+                }
+            }
+        }
+    }
+}
+
+pub fn read_messages<R: AsyncRead>(io: R) -> MessageStream<R> {
+    MessageStream::new(io)
+}
+
+/// Identifies a block across chains by its content hash (see
+/// [`Block::hash`](crate::Block)) rather than by the `parent_hash` a peer
+/// claims.
+type Identifier = ParentHash;
+
+/// Finds the most recent (highest-numbered) block shared by every supplied
+/// chain.
+///
+/// Each stream is drained concurrently, reporting `(stream_index, identifier,
+/// block_number, block)` tuples over an `mpsc` channel to a single aggregator which
+/// builds a per-stream `Identifier -> block_number` map plus a global
+/// `Identifier -> Block` map. After every stream drains (or one errors) the key
+/// sets of all per-stream maps are intersected and the identifier with the
+/// maximum `block_number` is returned.
+///
+/// Fewer than two streams is an error. An empty intersection yields
+/// `Ok(None)`. A stream that repeats an identifier is considered corrupt and
+/// dropped from the comparison.
+///
+/// The readers run as in-process futures joined with `future::join_all`
+/// rather than `tokio::spawn`ed tasks: `blockchain_streams` is a borrowed
+/// `&mut [BlockStream<R>]`, which is not `'static`, so the per-stream futures
+/// can't be spawned onto the runtime. They still make progress concurrently
+/// under the single `join_all`/`join` poll, just on this task rather than
+/// their own.
 pub async fn find_common_ancestor<R>(
     blockchain_streams: &mut [BlockStream<R>],
 ) -> Result<Option<Block>, Error>
 where
     R: tokio::io::AsyncRead + Unpin + Send,
 {
-    let stream_hashes: Rc<RefCell<HashMap<usize, HashSet<ParentHash>>>> = Rc::default();
-    let blocks: Rc<RefCell<HashMap<ParentHash, Block>>> = Rc::default();
-    let mut reads: Vec<LocalBoxFuture<()>> = vec![];
-
-    // TODO: Parallelize stream reads and search for common ancestor either by using seperate thread
-    // which receives stream id and hash via the channel or use some concurrent hashmap that is
-    // being read in parallel.
-    for (i, stream) in blockchain_streams.iter_mut().enumerate() {
-        let stream_hashes_populate = stream_hashes.clone();
-        let blocks = blocks.clone();
-        // Multiple streams are parsing the blocks.
-        let read = async move {
-            tokio::pin!(stream);
-            while let Some(res) = stream.next().await {
-                match res {
-                    // Every stream is populating the same hashmap.
-                    Ok(block) => {
-                        // Unique hash is expected here, consider stream invalid and end it.
-                        if stream_hashes_populate.borrow().get(&i).is_some() {
-                            return;
-                        } else {
-                            stream_hashes_populate
-                                .borrow_mut()
-                                .insert(i, HashSet::default());
-                            blocks.borrow_mut().insert(block.parent_hash, block);
+    if blockchain_streams.len() < 2 {
+        return Err(Error::other_error(
+            "at least two streams are required to find a common ancestor",
+        ));
+    }
+
+    let stream_count = blockchain_streams.len();
+    let (tx, mut rx) = mpsc::unbounded_channel::<(usize, Identifier, u64, Block)>();
+
+    // One concurrent reader per stream, each forwarding decoded blocks to the
+    // aggregator. A read error simply ends that stream's reader.
+    let reads = blockchain_streams
+        .iter_mut()
+        .enumerate()
+        .map(|(i, stream)| {
+            let tx = tx.clone();
+            async move {
+                tokio::pin!(stream);
+                while let Some(res) = stream.next().await {
+                    match res {
+                        Ok(block) => {
+                            let id = block.hash();
+                            let number = block.block_number;
+                            if tx.send((i, id, number, block)).is_err() {
+                                return;
+                            }
                         }
+                        Err(_) => return,
                     }
-                    Err(_) => return,
                 }
             }
-        };
-        reads.push(Box::pin(read));
-    }
+        })
+        .collect::<Vec<_>>();
+    // Drop the spare sender so the aggregator's recv loop ends once the readers
+    // (which hold the remaining clones) complete.
+    drop(tx);
+
+    let producer = future::join_all(reads);
+    let aggregator = async {
+        // Every stream gets a map, so one that yields no blocks still takes
+        // part in the intersection (and empties it).
+        let mut per_stream: HashMap<usize, HashMap<Identifier, u64>> =
+            (0..stream_count).map(|i| (i, HashMap::new())).collect();
+        let mut blocks: HashMap<Identifier, Block> = HashMap::new();
+        let mut invalid: HashSet<usize> = HashSet::new();
+
+        while let Some((i, id, number, block)) = rx.recv().await {
+            let entry = per_stream.entry(i).or_default();
+            if entry.insert(id, number).is_some() {
+                // A repeated identifier means the stream cannot be trusted.
+                invalid.insert(i);
+            }
+            blocks.entry(id).or_insert(block);
+        }
 
-    // Running streams concurrently
-    future::join_all(reads).await;
+        (per_stream, blocks, invalid)
+    };
 
-    // Filter the hashmap for common hashes.
-    let mut common_hashes: HashSet<ParentHash> = HashSet::default();
-    for (_, hashes) in stream_hashes.borrow_mut().iter() {
-        common_hashes = common_hashes
-            .intersection(hashes)
-            .map(|x| x.to_owned())
-            .collect();
+    let (_, (per_stream, blocks, invalid)) = future::join(producer, aggregator).await;
+
+    let maps: Vec<&HashMap<Identifier, u64>> = per_stream
+        .iter()
+        .filter(|(i, _)| !invalid.contains(i))
+        .map(|(_, map)| map)
+        .collect();
+
+    // Without at least two trustworthy chains there is nothing to compare.
+    if maps.len() < 2 {
+        return Ok(None);
     }
 
-    // TODO: fix this to return the most recent common ancestor.
-    if common_hashes.is_empty() {
-        Ok(None)
-    } else {
-        let random_common_hash = common_hashes.iter().next().unwrap();
-        let block = blocks.borrow().get(random_common_hash).unwrap().clone();
-        Ok(Some(block))
+    let mut best: Option<(Identifier, u64)> = None;
+    for (id, &number) in maps[0] {
+        if maps[1..].iter().all(|map| map.contains_key(id)) {
+            match best {
+                Some((_, best_number)) if best_number >= number => {}
+                _ => best = Some((*id, number)),
+            }
+        }
     }
+
+    Ok(best.map(|(id, _)| blocks.get(&id).unwrap().clone()))
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::stream::read_blocks;
+    use crate::stream::{read_blocks, read_messages};
     // TODO: This feels like a hack just to be able to use futures_mockstream::MockStream.
     // Change this to use a mocked stream that implements tokio::io::AsyncRead or add
     // compatibility directly to the BlockStreamm.
+    use super::{find_common_ancestor, Block};
+    use crate::codec::BlockCodec;
     use crate::utils::get_dummy_block_pair;
+    use bytes::{BufMut, Bytes, BytesMut};
     use futures::StreamExt;
+    use tokio_util::codec::Encoder;
     use tokio_util::compat::FuturesAsyncReadCompatExt;
 
+    fn encode_blocks(blocks: &[Block]) -> Bytes {
+        let mut codec = BlockCodec::new();
+        let mut dst = BytesMut::new();
+        for block in blocks {
+            codec.encode(block.clone(), &mut dst).unwrap();
+        }
+        dst.freeze()
+    }
+
     #[tokio::test]
     async fn test_read_blocks_fn() {
         use futures_mockstream::MockStream;
@@ -175,4 +370,161 @@ mod tests {
             assert_eq!(expected.content, block.content);
         }
     }
+
+    #[tokio::test]
+    async fn test_max_frame_length_rejects_oversized_frame() {
+        use futures_mockstream::MockStream;
+
+        let (_, reader) = get_dummy_block_pair();
+
+        let ms = MockStream::from(&reader);
+        let mut bs = read_blocks(ms.compat()).max_frame_length(1);
+        let err = bs.next().await.unwrap().unwrap_err();
+
+        assert!(matches!(
+            err.kind(),
+            crate::ErrorKind::FrameTooLarge { max: 1, .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_find_common_ancestor_requires_two_streams() {
+        let bytes = encode_blocks(&[Block::new([0u8; 32], 0, b"genesis")]);
+        let mut streams = [read_blocks(futures_mockstream::MockStream::from(&bytes).compat())];
+
+        assert!(find_common_ancestor(&mut streams).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_find_common_ancestor_disjoint_chains_returns_none() {
+        let a = encode_blocks(&[Block::new([0u8; 32], 0, b"a-genesis")]);
+        let b = encode_blocks(&[Block::new([0u8; 32], 0, b"b-genesis")]);
+        let mut streams = [
+            read_blocks(futures_mockstream::MockStream::from(&a).compat()),
+            read_blocks(futures_mockstream::MockStream::from(&b).compat()),
+        ];
+
+        let ancestor = find_common_ancestor(&mut streams).await.unwrap();
+        assert!(ancestor.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_find_common_ancestor_picks_most_recent_shared_block() {
+        let genesis = Block::new([0u8; 32], 0, b"genesis");
+        let older = Block::new(genesis.hash(), 1, b"older-common");
+        let newer = Block::new(older.hash(), 2, b"newer-common");
+
+        let a = encode_blocks(&[genesis.clone(), older.clone(), newer.clone()]);
+        let b = encode_blocks(&[genesis, older, newer.clone()]);
+        let mut streams = [
+            read_blocks(futures_mockstream::MockStream::from(&a).compat()),
+            read_blocks(futures_mockstream::MockStream::from(&b).compat()),
+        ];
+
+        let ancestor = find_common_ancestor(&mut streams).await.unwrap().unwrap();
+        assert_eq!(newer.block_number, ancestor.block_number);
+        assert_eq!(newer.hash(), ancestor.hash());
+    }
+
+    #[tokio::test]
+    async fn test_find_common_ancestor_excludes_stream_with_duplicate_identifier() {
+        let shared = Block::new([0u8; 32], 0, b"shared");
+
+        // Stream 0 reports the same identifier twice, which marks it invalid
+        // and drops it from the comparison entirely, even though `shared` is
+        // also present in stream 1.
+        let a = encode_blocks(&[shared.clone(), shared.clone()]);
+        let b = encode_blocks(&[shared]);
+        let mut streams = [
+            read_blocks(futures_mockstream::MockStream::from(&a).compat()),
+            read_blocks(futures_mockstream::MockStream::from(&b).compat()),
+        ];
+
+        let ancestor = find_common_ancestor(&mut streams).await.unwrap();
+        assert!(ancestor.is_none());
+    }
+
+    // Message frame: version (low nibble) + message type (high nibble), a
+    // 4-byte big-endian length prefix, then the payload. Mirrors
+    // BlockCodec::encode; TYPE_PONG (0x3) is used since it has no payload.
+    fn pong_frame() -> Bytes {
+        let mut buf = BytesMut::new();
+        buf.put_u8((0x3 << 4) | 0x1);
+        buf.put_u32(0);
+        buf.freeze()
+    }
+
+    #[tokio::test]
+    async fn test_read_messages_decodes_pong() {
+        use futures_mockstream::MockStream;
+
+        let bytes = pong_frame();
+        let ms = MockStream::from(&bytes);
+        let mut ms = read_messages(ms.compat());
+
+        assert!(matches!(
+            ms.next().await.unwrap().unwrap(),
+            crate::Message::Pong
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_read_messages_rejects_oversized_frame() {
+        use futures_mockstream::MockStream;
+
+        // TYPE_GET_BLOCKS (0x1) with an 8-byte payload, which exceeds the
+        // max_frame_length of 1 set below.
+        let mut buf = BytesMut::new();
+        buf.put_u8((0x1 << 4) | 0x1);
+        buf.put_u32(8);
+        buf.put_u64(0);
+        let bytes = buf.freeze();
+
+        let ms = MockStream::from(&bytes);
+        let mut ms = read_messages(ms.compat()).max_frame_length(1);
+        let err = ms.next().await.unwrap().unwrap_err();
+
+        assert!(matches!(
+            err.kind(),
+            crate::ErrorKind::FrameTooLarge { max: 1, .. }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_read_messages_rejects_unsupported_version() {
+        use futures_mockstream::MockStream;
+
+        let mut buf = BytesMut::new();
+        buf.put_u8((0x3 << 4) | 0x2);
+        buf.put_u32(0);
+        let bytes = buf.freeze();
+
+        let ms = MockStream::from(&bytes);
+        let mut ms = read_messages(ms.compat());
+        let err = ms.next().await.unwrap().unwrap_err();
+
+        assert!(matches!(
+            err.kind(),
+            crate::ErrorKind::UnsupportedVersion { version: 0x2 }
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_read_messages_rejects_unknown_message_type() {
+        use futures_mockstream::MockStream;
+
+        let mut buf = BytesMut::new();
+        buf.put_u8((0xF << 4) | 0x1);
+        buf.put_u32(0);
+        let bytes = buf.freeze();
+
+        let ms = MockStream::from(&bytes);
+        let mut ms = read_messages(ms.compat());
+        let err = ms.next().await.unwrap().unwrap_err();
+
+        assert!(matches!(
+            err.kind(),
+            crate::ErrorKind::UnknownMessageType { message_type: 0xF }
+        ));
+    }
 }
@@ -0,0 +1,125 @@
+use futures::prelude::*;
+use pin_project::pin_project;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use super::block::{BlockHasher, ParentHash, Sha256Hasher};
+use super::error::Error;
+use super::Block;
+
+/// Wraps a stream of [`Block`]s and verifies chain linkage as they pass
+/// through.
+///
+/// Each accepted block is hashed (via the pluggable [`BlockHasher`]) and the
+/// next block must declare that hash as its `parent_hash` and carry a
+/// `block_number` exactly one higher. The first block seeds the chain. On a
+/// mismatch the stream yields
+/// [`ErrorKind::BrokenChain`](crate::ErrorKind::BrokenChain) once and then
+/// ends.
+#[pin_project]
+pub struct VerifiedBlockStream<S, H = Sha256Hasher> {
+    #[pin]
+    inner: S,
+    hasher: H,
+    // Hash and number of the last accepted block, `None` until the first one.
+    prev: Option<(ParentHash, u64)>,
+    done: bool,
+}
+
+impl<S> VerifiedBlockStream<S, Sha256Hasher>
+where
+    S: Stream<Item = super::Result<Block>>,
+{
+    pub fn new(inner: S) -> Self {
+        VerifiedBlockStream::with_hasher(inner, Sha256Hasher)
+    }
+}
+
+impl<S, H> VerifiedBlockStream<S, H>
+where
+    S: Stream<Item = super::Result<Block>>,
+    H: BlockHasher,
+{
+    pub fn with_hasher(inner: S, hasher: H) -> Self {
+        VerifiedBlockStream {
+            inner,
+            hasher,
+            prev: None,
+            done: false,
+        }
+    }
+}
+
+impl<S, H> Stream for VerifiedBlockStream<S, H>
+where
+    S: Stream<Item = super::Result<Block>>,
+    H: BlockHasher,
+{
+    type Item = super::Result<Block>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.project();
+
+        if *this.done {
+            return Poll::Ready(None);
+        }
+
+        match this.inner.poll_next(cx) {
+            Poll::Pending => Poll::Pending,
+            Poll::Ready(None) => Poll::Ready(None),
+            Poll::Ready(Some(Err(e))) => {
+                *this.done = true;
+                Poll::Ready(Some(Err(e)))
+            }
+            Poll::Ready(Some(Ok(block))) => {
+                if let Some((expected, prev_number)) = *this.prev {
+                    if block.parent_hash != expected || block.block_number != prev_number + 1 {
+                        *this.done = true;
+                        return Poll::Ready(Some(Err(Error::broken_chain_error(
+                            expected,
+                            block.parent_hash,
+                            block.block_number,
+                        ))));
+                    }
+                }
+
+                *this.prev = Some((this.hasher.hash(&block), block.block_number));
+                Poll::Ready(Some(Ok(block)))
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::block::BlockHasher;
+
+    #[tokio::test]
+    async fn test_accepts_linked_chain() {
+        let genesis = Block::new([0u8; 32], 0, b"genesis");
+        let second = Block::new(Sha256Hasher.hash(&genesis), 1, b"second");
+
+        let stream = futures::stream::iter(vec![Ok(genesis), Ok(second)]);
+        let verified: Vec<_> = VerifiedBlockStream::new(stream).collect().await;
+
+        assert!(verified.iter().all(|r| r.is_ok()));
+        assert_eq!(2, verified.len());
+    }
+
+    #[tokio::test]
+    async fn test_rejects_broken_link() {
+        let genesis = Block::new([0u8; 32], 0, b"genesis");
+        // Wrong parent_hash breaks the chain.
+        let second = Block::new([9u8; 32], 1, b"second");
+
+        let stream = futures::stream::iter(vec![Ok(genesis), Ok(second)]);
+        let verified: Vec<_> = VerifiedBlockStream::new(stream).collect().await;
+
+        assert!(verified[0].is_ok());
+        assert!(matches!(
+            verified[1].as_ref().unwrap_err().kind(),
+            crate::ErrorKind::BrokenChain { .. }
+        ));
+    }
+}